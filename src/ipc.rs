@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+/// A single control message sent from a client invocation to a running daemon.
+#[derive(Deserialize, Serialize, Debug)]
+pub enum IpcMessage {
+    Next,
+    Toggle,
+    Current,
+    Pause,
+    Resume,
+    SetActiveDir(usize),
+    SetDuration(String),
+}
+
+/// The state the daemon reports back after handling a message.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct IpcReply {
+    pub current: Option<String>,
+    pub active_dir: usize,
+    pub duration: String,
+    pub paused: bool,
+}
+
+/// Path of the daemon control socket, under `$XDG_RUNTIME_DIR` when available
+/// and `/tmp` otherwise.
+pub fn socket_path() -> PathBuf {
+    let dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| String::from("/tmp"));
+    PathBuf::from(dir).join("wallch.sock")
+}
+
+/// Connect to a running daemon, send a single message and return its reply.
+pub fn send(msg: &IpcMessage) -> Result<IpcReply> {
+    let mut stream = UnixStream::connect(socket_path())
+        .with_context(|| "Could not connect to wallch daemon socket")?;
+
+    let mut line = serde_json::to_string(msg).with_context(|| "Could not serialize IPC message")?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .with_context(|| "Could not send IPC message")?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .with_context(|| "Could not read IPC reply")?;
+
+    serde_json::from_str(&reply).with_context(|| "Could not parse IPC reply")
+}
@@ -1,19 +1,68 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::{App, Arg, SubCommand};
 use dirs;
-use glob::glob;
 use rand::rngs::ThreadRng;
 use rand::seq::IteratorRandom;
 use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::Duration;
+
+mod ipc;
+mod schedule;
+
+use ipc::{IpcMessage, IpcReply};
+use schedule::ScheduleEntry;
 
 #[derive(Deserialize, Serialize)]
 struct Config {
     dirs: Vec<String>,
+    dark_dirs: Option<Vec<String>>,
     duration: Option<String>,
     active_dir: Option<usize>,
     current: Option<String>,
+    current_dark: Option<String>,
     next: Option<Vec<String>>,
+    next_dark: Option<Vec<String>>,
+    mode: Option<String>,
+    recursive: Option<bool>,
+    extensions: Option<Vec<String>>,
+    schedule: Option<Vec<ScheduleEntry>>,
+}
+
+/// The image extensions the selector considers by default
+fn default_extensions() -> Vec<String> {
+    ["png", "jpg", "jpeg", "webp", "gif"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// The values GNOME accepts for the `picture-options` gsettings key
+const PICTURE_OPTIONS: [&str; 7] = [
+    "none",
+    "wallpaper",
+    "centered",
+    "scaled",
+    "stretched",
+    "zoom",
+    "spanned",
+];
+
+/// Ensure a picture-options mode is one GNOME understands
+fn validate_mode(mode: &str) -> Result<()> {
+    if PICTURE_OPTIONS.contains(&mode) {
+        Ok(())
+    } else {
+        bail!(
+            "Invalid picture mode '{}' (expected one of: {})",
+            mode,
+            PICTURE_OPTIONS.join(", ")
+        )
+    }
 }
 
 /// Open a given config file and try to parse the contents into a Config struct
@@ -34,6 +83,33 @@ fn parse_config(fname: &String) -> Result<Config> {
     config.duration = Some(duration);
     config.active_dir = Some(active_dir);
 
+    // Dark dirs are indexed in parallel with `dirs`, so they must line up
+    if let Some(dark_dirs) = &config.dark_dirs {
+        if dark_dirs.len() != config.dirs.len() {
+            bail!(
+                "`dark_dirs` must have the same number of entries as `dirs` ({} vs {})",
+                dark_dirs.len(),
+                config.dirs.len()
+            );
+        }
+    }
+
+    // Schedule entries reference `dirs` by index, so check them before use
+    if let Some(schedule) = &config.schedule {
+        for entry in schedule {
+            if let Some(dir) = entry.dir {
+                if dir >= config.dirs.len() {
+                    bail!(
+                        "Schedule entry {} references dir index {} but only {} dirs are configured",
+                        entry.time,
+                        dir,
+                        config.dirs.len()
+                    );
+                }
+            }
+        }
+    }
+
     Ok(config)
 }
 
@@ -48,27 +124,59 @@ fn write_config(config: &Config, fname: &String) -> Result<()> {
 }
 
 /// Randomly select a new wallpaper from the given directory
-fn select_new(dir: &String, rng: &mut ThreadRng) -> Result<String> {
-    let imgs = glob(format!("{}/*.*", dir).as_str())
-        .with_context(|| format!("Could not read dir: {}", dir))?;
-
-    let img = imgs
+///
+/// Walks the directory (recursively when `recursive` is set) and only considers
+/// files whose extension is in `extensions`, so nested theme folders are picked
+/// up and non-image files are ignored.
+fn select_new(
+    dir: &String,
+    recursive: bool,
+    extensions: &[String],
+    rng: &mut ThreadRng,
+) -> Result<String> {
+    let walker = WalkDir::new(dir).max_depth(if recursive { usize::MAX } else { 1 });
+
+    let img = walker
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(ext)))
+                .unwrap_or(false)
+        })
+        .map(|entry| entry.into_path())
         .choose(rng)
         .with_context(|| format!("Could not pick image from dir: {}", dir))?;
 
-    Ok(format!("file://{}", img.unwrap().display()))
+    Ok(format!("file://{}", img.display()))
 }
 
 /// Select the next wallpaper for each directory and "cache" it (i.e. store it in config)
 fn cache_next(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
     let mut config = parse_config(&config_str)?;
 
+    let recursive = config.recursive.unwrap_or(false);
+    let extensions = config.extensions.clone().unwrap_or_else(default_extensions);
+
     let mut next: Vec<String> = Vec::new();
     for dir in &config.dirs {
-        next.push(select_new(&dir, rng)?);
+        next.push(select_new(&dir, recursive, &extensions, rng)?);
     }
-
     config.next = Some(next);
+
+    // Cache a parallel dark-mode selection when dark dirs are configured
+    if let Some(dark_dirs) = &config.dark_dirs {
+        let mut next_dark: Vec<String> = Vec::new();
+        for dir in dark_dirs {
+            next_dark.push(select_new(&dir, recursive, &extensions, rng)?);
+        }
+        config.next_dark = Some(next_dark);
+    }
+
     write_config(&config, config_str)?;
 
     Ok(())
@@ -84,33 +192,130 @@ fn get_next(config_str: &String, rng: &mut ThreadRng) -> Result<String> {
         Ok(String::from(&next[active_dir]))
     } else {
         // No pre-set next wallpaper; select one on the fly
-        Ok(select_new(&config.dirs[active_dir], rng)?)
+        let recursive = config.recursive.unwrap_or(false);
+        let extensions = config.extensions.clone().unwrap_or_else(default_extensions);
+        Ok(select_new(&config.dirs[active_dir], recursive, &extensions, rng)?)
     }
 }
 
-/// Set the wallpaper to a given file
-fn set_wallpaper(fname: &String) -> Result<()> {
+/// Get the next cached dark-mode wallpaper, if dark dirs are configured
+fn get_next_dark(config_str: &String, rng: &mut ThreadRng) -> Result<Option<String>> {
+    let config = parse_config(&config_str)?;
+    let active_dir = config.active_dir.unwrap_or(0);
+
+    let dark_dirs = match &config.dark_dirs {
+        Some(dark_dirs) => dark_dirs,
+        None => return Ok(None),
+    };
+
+    if let Some(next_dark) = &config.next_dark {
+        // Next dark wallpaper has been pre-set; return it
+        Ok(Some(String::from(&next_dark[active_dir])))
+    } else {
+        // No pre-set next dark wallpaper; select one on the fly
+        let recursive = config.recursive.unwrap_or(false);
+        let extensions = config.extensions.clone().unwrap_or_else(default_extensions);
+        Ok(Some(select_new(&dark_dirs[active_dir], recursive, &extensions, rng)?))
+    }
+}
+
+/// Query GNOME's interface color scheme to see whether dark mode is active
+fn dark_mode_active() -> bool {
+    std::process::Command::new("gsettings")
+        .args(&["get", "org.gnome.desktop.interface", "color-scheme"])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).contains("dark"))
+        .unwrap_or(false)
+}
+
+/// Set the wallpaper to a given file, also setting the dark-mode wallpaper when
+/// one is supplied so the light and dark desktops stay in sync.
+fn set_wallpaper(fname: &String, dark: Option<&String>, mode: Option<&String>) -> Result<()> {
     std::process::Command::new("gsettings")
         .args(&["set", "org.gnome.desktop.background", "picture-uri", &fname])
         .status()
         .with_context(|| "Could not set desktop background")?;
 
+    if let Some(dark) = dark {
+        std::process::Command::new("gsettings")
+            .args(&["set", "org.gnome.desktop.background", "picture-uri-dark", &dark])
+            .status()
+            .with_context(|| "Could not set dark desktop background")?;
+    }
+
+    // Apply the scaling mode whenever one is configured
+    if let Some(mode) = mode {
+        validate_mode(mode)?;
+        std::process::Command::new("gsettings")
+            .args(&["set", "org.gnome.desktop.background", "picture-options", &mode])
+            .status()
+            .with_context(|| "Could not set picture options")?;
+    }
+
     Ok(())
 }
 
-/// Perform one iteration of the change-wallpaper-and-sleep cycle
-fn run(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
-    // We re-read the config in every loop iteration so it can be changed on the fly
+/// Apply the next wallpaper for this cycle and return how long to wait before
+/// the following one. Shared by the single-iteration `run` helper and the
+/// daemon, which differ only in how they wait out the returned duration.
+fn apply_change(config_str: &String, rng: &mut ThreadRng) -> Result<Duration> {
+    // We re-read the config in every cycle so it can be changed on the fly
     let mut config = parse_config(&config_str)?;
 
+    // When a schedule is present, it drives both the active dir/wallpaper and
+    // the sleep interval; the flat `duration` mode is only the fallback.
+    if let Some(schedule) = config.schedule.clone() {
+        if !schedule.is_empty() {
+            let (slot, sleep) = schedule::current_slot(&schedule)?;
+
+            let (current, current_dark) = if let Some(wallpaper) = slot.wallpaper {
+                // A specific wallpaper is forced for this slot
+                let forced = if wallpaper.starts_with("file://") {
+                    wallpaper
+                } else {
+                    format!("file://{}", wallpaper)
+                };
+                (forced, None)
+            } else {
+                // Switch to the slot's directory and pick a random wallpaper
+                if let Some(dir) = slot.dir {
+                    config.active_dir = Some(dir);
+                }
+                let active_dir = config.active_dir.unwrap_or(0);
+                let recursive = config.recursive.unwrap_or(false);
+                let extensions = config.extensions.clone().unwrap_or_else(default_extensions);
+                let light = select_new(&config.dirs[active_dir], recursive, &extensions, rng)?;
+                let dark = match &config.dark_dirs {
+                    Some(dark_dirs) => {
+                        Some(select_new(&dark_dirs[active_dir], recursive, &extensions, rng)?)
+                    }
+                    None => None,
+                };
+                (light, dark)
+            };
+
+            set_wallpaper(&current, current_dark.as_ref(), config.mode.as_ref())?;
+
+            config.current = Some(current);
+            config.current_dark = current_dark;
+            write_config(&config, config_str)?;
+
+            cache_next(&config_str, rng)?;
+
+            return Ok(sleep);
+        }
+    }
+
     // Get or select the next WP
     let current = get_next(&config_str, rng)?;
+    let current_dark = get_next_dark(&config_str, rng)?;
 
     // Set it
-    set_wallpaper(&current)?;
+    set_wallpaper(&current, current_dark.as_ref(), config.mode.as_ref())?;
 
     // Save it to config
     config.current = Some(current);
+    config.current_dark = current_dark;
     write_config(&config, config_str)?;
 
     // Pre-select the next WP
@@ -121,8 +326,33 @@ fn run(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
     let duration = humanize_rs::duration::parse(&duration)
         .with_context(|| format!("Could not parse duration"))?;
 
-    thread::sleep(duration);
+    Ok(duration)
+}
+
+/// Compute how long to wait before the next change without touching the
+/// wallpaper — used by the daemon to recompute its sleep after a control
+/// message that did not itself advance the rotation.
+fn compute_sleep(config_str: &String) -> Result<Duration> {
+    let config = parse_config(config_str)?;
+
+    if let Some(schedule) = &config.schedule {
+        if !schedule.is_empty() {
+            let (_slot, sleep) = schedule::current_slot(schedule)?;
+            return Ok(sleep);
+        }
+    }
+
+    let duration = config.duration.unwrap_or(String::from("10m"));
+    let duration = humanize_rs::duration::parse(&duration)
+        .with_context(|| format!("Could not parse duration"))?;
+
+    Ok(duration)
+}
 
+/// Perform one iteration of the change-wallpaper-and-sleep cycle
+fn run(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
+    let duration = apply_change(config_str, rng)?;
+    thread::sleep(duration);
     Ok(())
 }
 
@@ -131,9 +361,11 @@ fn next(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
     let mut config = parse_config(&config_str)?;
 
     let current = get_next(config_str, rng)?;
-    set_wallpaper(&current)?;
+    let current_dark = get_next_dark(config_str, rng)?;
+    set_wallpaper(&current, current_dark.as_ref(), config.mode.as_ref())?;
 
     config.current = Some(current);
+    config.current_dark = current_dark;
     write_config(&config, config_str)?;
 
     cache_next(&config_str, rng)?;
@@ -164,7 +396,12 @@ fn toggle(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
 
 /// Return the path of the current wallpaper file, stripped of the "file://" prefix
 fn current(config: &Config) -> Option<String> {
-    let current = &config.current;
+    // Report the dark wallpaper when the desktop is in dark mode and one is set
+    let current = if dark_mode_active() && config.current_dark.is_some() {
+        &config.current_dark
+    } else {
+        &config.current
+    };
 
     match current {
         Some(s) => Some(s.replace("file://", "")),
@@ -172,6 +409,151 @@ fn current(config: &Config) -> Option<String> {
     }
 }
 
+/// Copy the current wallpaper image to a destination path, creating parent
+/// directories as needed
+fn save_wallpaper(src: &str, dest: &str) -> Result<()> {
+    let dest_path = std::path::Path::new(dest);
+
+    if let Some(parent) = dest_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create parent dirs: {}", parent.display()))?;
+        }
+    }
+
+    std::fs::copy(src, dest_path)
+        .with_context(|| format!("Could not save wallpaper to: {}", dest))?;
+
+    Ok(())
+}
+
+/// Build an `IpcReply` describing the current on-disk state
+fn state_reply(config_str: &String, paused: bool) -> Result<IpcReply> {
+    let config = parse_config(config_str)?;
+
+    Ok(IpcReply {
+        current: current(&config),
+        active_dir: config.active_dir.unwrap_or(0),
+        duration: config.duration.unwrap_or(String::from("10m")),
+        paused,
+    })
+}
+
+/// Handle a single client connection: read one message, apply it and reply
+/// with the resulting state.
+fn handle_client(
+    stream: UnixStream,
+    config_str: &String,
+    rng: &mut ThreadRng,
+    state: &Arc<(Mutex<bool>, Condvar)>,
+) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(stream.try_clone().with_context(|| "Could not clone IPC stream")?)
+        .read_line(&mut line)
+        .with_context(|| "Could not read IPC message")?;
+
+    let msg: IpcMessage =
+        serde_json::from_str(line.trim()).with_context(|| "Could not parse IPC message")?;
+
+    let (lock, cvar) = &**state;
+    let mut paused = lock.lock().unwrap();
+
+    // `Current` is read-only: it must neither change the wallpaper nor wake the
+    // change loop. Every other message mutates state the loop cares about.
+    let notify = !matches!(msg, IpcMessage::Current);
+
+    match msg {
+        IpcMessage::Next => next(config_str, rng)?,
+        IpcMessage::Toggle => toggle(config_str, rng)?,
+        IpcMessage::Current => {}
+        IpcMessage::Pause => *paused = true,
+        IpcMessage::Resume => *paused = false,
+        IpcMessage::SetActiveDir(dir) => {
+            let mut config = parse_config(config_str)?;
+            config.active_dir = Some(dir);
+            write_config(&config, config_str)?;
+        }
+        IpcMessage::SetDuration(duration) => {
+            let mut config = parse_config(config_str)?;
+            config.duration = Some(duration);
+            write_config(&config, config_str)?;
+        }
+    }
+
+    // Wake the change loop so it re-reads the config and recomputes its sleep.
+    // It only *applies* a new wallpaper on an actual timeout, so this never
+    // double-advances after `Next`/`Toggle` already changed it here.
+    if notify {
+        cvar.notify_all();
+    }
+
+    let reply = state_reply(config_str, *paused)?;
+    drop(paused);
+
+    let mut out = serde_json::to_string(&reply).with_context(|| "Could not serialize IPC reply")?;
+    out.push('\n');
+    let mut stream = stream;
+    stream
+        .write_all(out.as_bytes())
+        .with_context(|| "Could not send IPC reply")?;
+
+    Ok(())
+}
+
+/// Run the change cycle while listening on a Unix socket for control messages,
+/// so `next`/`toggle`/`pause`/... take effect on the live loop instantly.
+fn daemon(config_str: &String, rng: &mut ThreadRng) -> Result<()> {
+    let path = ipc::socket_path();
+    // Clear a stale socket left over from a previous daemon
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Could not bind daemon socket: {}", path.display()))?;
+
+    let state = Arc::new((Mutex::new(false), Condvar::new()));
+
+    // Listener thread applies messages under the shared lock so it never races
+    // the change loop on the config file
+    {
+        let state = Arc::clone(&state);
+        let config_str = config_str.clone();
+        thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                if let Err(e) = handle_client(stream, &config_str, &mut rng, &state) {
+                    eprintln!("IPC error: {:#}", e);
+                }
+            }
+        });
+    }
+
+    let (lock, cvar) = &*state;
+    let mut guard = lock.lock().unwrap();
+
+    // Apply the first wallpaper, then only change again when a sleep actually
+    // elapses. A message merely wakes us to recompute the sleep target; it does
+    // not by itself advance the wallpaper (the client already did that).
+    let mut sleep = apply_change(config_str, rng)?;
+    loop {
+        let (next_guard, timeout) = cvar.wait_timeout(guard, sleep).unwrap();
+        guard = next_guard;
+
+        sleep = if *guard {
+            // Paused: wait to be woken by Resume
+            Duration::from_secs(3600)
+        } else if timeout.timed_out() {
+            // A schedule/interval boundary was reached: change the wallpaper
+            apply_change(config_str, rng)?
+        } else {
+            // Woken by a message: just recompute the wait, leaving the wallpaper
+            compute_sleep(config_str)?
+        };
+    }
+}
+
 fn main() -> Result<()> {
     let matches = App::new("GNOME Wallpape-rs")
         .version("0.1.0")
@@ -201,13 +583,34 @@ fn main() -> Result<()> {
                 .help("Set active wallpaper directory (index of dirs vector)")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("mode")
+                .short("m")
+                .long("mode")
+                .value_name("MODE")
+                .help("Set picture scaling mode (picture-options)")
+                .takes_value(true),
+        )
         .subcommand(SubCommand::with_name("run").about("Starts the wallpaper changer loop"))
         .subcommand(SubCommand::with_name("next").about("Change to a new wallpaper"))
         .subcommand(
             SubCommand::with_name("toggle")
                 .about("Change wallpaper directory and apply a new wallpaper"),
         )
-        .subcommand(SubCommand::with_name("current").about("Print current wallpaper path"))
+        .subcommand(
+            SubCommand::with_name("current")
+                .about("Print current wallpaper path")
+                .arg(
+                    Arg::with_name("save")
+                        .short("s")
+                        .long("save")
+                        .value_name("PATH")
+                        .help("Copy the current wallpaper to the given path")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(SubCommand::with_name("pause").about("Pause the running daemon's loop"))
+        .subcommand(SubCommand::with_name("resume").about("Resume the running daemon's loop"))
         .get_matches();
 
     let home_dir = dirs::home_dir().unwrap().to_str().unwrap().to_string();
@@ -220,31 +623,74 @@ fn main() -> Result<()> {
     );
     let mut config = parse_config(&config_str)?;
 
-    // Use user-specified duration if present
-    if let Some(d) = matches.value_of("duration") {
-        config.duration = Some(String::from(d));
-    }
+    // When a daemon is already running, duration/active changes and the client
+    // subcommands are sent to it over the socket rather than mutating the TOML.
+    let daemon_up = UnixStream::connect(ipc::socket_path()).is_ok();
 
-    // Use user-specified active directory if present
-    if let Some(a) = matches.value_of("active") {
-        config.active_dir = Some(a.parse()?);
-    }
+    let mut rng = rand::thread_rng();
 
-    // Config has been updated with values for all optional parameters; save it back to file
-    write_config(&config, &config_str)?;
+    if daemon_up {
+        if let Some(d) = matches.value_of("duration") {
+            ipc::send(&IpcMessage::SetDuration(String::from(d)))?;
+        }
+        if let Some(a) = matches.value_of("active") {
+            ipc::send(&IpcMessage::SetActiveDir(a.parse()?))?;
+        }
+    } else {
+        // Use user-specified duration if present
+        if let Some(d) = matches.value_of("duration") {
+            config.duration = Some(String::from(d));
+        }
 
-    let mut rng = rand::thread_rng();
+        // Use user-specified active directory if present
+        if let Some(a) = matches.value_of("active") {
+            config.active_dir = Some(a.parse()?);
+        }
+
+        // Config has been updated with values for all optional parameters; save it back to file
+        write_config(&config, &config_str)?;
+    }
+
+    // Picture mode has no live IPC message; it is config-only and picked up on
+    // the next change cycle, so persist it whether or not a daemon is running.
+    if let Some(m) = matches.value_of("mode") {
+        validate_mode(m)?;
+        // Re-read so changes a daemon just saved are not overwritten
+        config = parse_config(&config_str)?;
+        config.mode = Some(String::from(m));
+        write_config(&config, &config_str)?;
+    }
 
     if let Some(_) = matches.subcommand_matches("run") {
-        loop {
-            run(&config_str, &mut rng)?;
-        }
+        daemon(&config_str, &mut rng)?;
     } else if let Some(_) = matches.subcommand_matches("next") {
-        next(&config_str, &mut rng)?;
+        // Talk to a live daemon when present; otherwise change directly
+        match ipc::send(&IpcMessage::Next) {
+            Ok(_) => {}
+            Err(_) => next(&config_str, &mut rng)?,
+        }
     } else if let Some(_) = matches.subcommand_matches("toggle") {
-        toggle(&config_str, &mut rng)?;
-    } else if let Some(_) = matches.subcommand_matches("current") {
-        println!("{}", current(&config).unwrap_or(String::new()));
+        match ipc::send(&IpcMessage::Toggle) {
+            Ok(_) => {}
+            Err(_) => toggle(&config_str, &mut rng)?,
+        }
+    } else if let Some(current_matches) = matches.subcommand_matches("current") {
+        // Prefer the live daemon's view of the current wallpaper when present
+        let path = match ipc::send(&IpcMessage::Current) {
+            Ok(reply) => reply.current,
+            Err(_) => current(&config),
+        };
+
+        if let Some(dest) = current_matches.value_of("save") {
+            let src = path.with_context(|| "No wallpaper is currently set")?;
+            save_wallpaper(&src, dest)?;
+        } else {
+            println!("{}", path.unwrap_or(String::new()));
+        }
+    } else if let Some(_) = matches.subcommand_matches("pause") {
+        ipc::send(&IpcMessage::Pause)?;
+    } else if let Some(_) = matches.subcommand_matches("resume") {
+        ipc::send(&IpcMessage::Resume)?;
     }
 
     Ok(())
@@ -0,0 +1,81 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A single time-of-day schedule entry.
+///
+/// The `time` is given in 24-hour `HH:MM` form. When the entry becomes active
+/// it either switches to the directory at index `dir` (and a random wallpaper
+/// is picked from it) or, if `wallpaper` is set, forces that specific file.
+///
+/// Only fixed `HH:MM` boundaries are supported; cron-style expressions are not
+/// yet implemented.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ScheduleEntry {
+    pub time: String,
+    pub dir: Option<usize>,
+    pub wallpaper: Option<String>,
+}
+
+/// Parse an `HH:MM` string into minutes since midnight
+fn minutes_of_day(time: &str) -> Result<u32> {
+    let (h, m) = time
+        .split_once(':')
+        .with_context(|| format!("Invalid schedule time (expected HH:MM): {}", time))?;
+
+    let h: u32 = h
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid schedule hour: {}", time))?;
+    let m: u32 = m
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid schedule minute: {}", time))?;
+
+    if h > 23 || m > 59 {
+        bail!("Schedule time out of range (expected 00:00..23:59): {}", time);
+    }
+
+    Ok(h * 60 + m)
+}
+
+/// Given the schedule and the current local time, return the entry that is
+/// currently in effect (the most recent boundary at or before now) together
+/// with the time to sleep until the next boundary.
+///
+/// Boundaries wrap around midnight: if no entry is at or before `now`, the last
+/// entry of the (sorted) day is still in effect until today's earliest entry.
+pub fn current_slot(schedule: &[ScheduleEntry]) -> Result<(ScheduleEntry, Duration)> {
+    if schedule.is_empty() {
+        bail!("Cannot compute schedule slot from an empty schedule");
+    }
+
+    let mut entries: Vec<(u32, &ScheduleEntry)> = schedule
+        .iter()
+        .map(|e| Ok((minutes_of_day(&e.time)?, e)))
+        .collect::<Result<_>>()?;
+    entries.sort_by_key(|(mins, _)| *mins);
+
+    let now = Local::now();
+    let now_min = now.hour() * 60 + now.minute();
+
+    // Most recent boundary at or before now, else wrap to the last entry
+    let idx = entries
+        .iter()
+        .rposition(|(mins, _)| *mins <= now_min)
+        .unwrap_or(entries.len() - 1);
+    let active = entries[idx].1.clone();
+
+    // Sleep until the next boundary, wrapping past midnight if necessary
+    let next_min = entries[(idx + 1) % entries.len()].0;
+    let now_sec = now.hour() * 3600 + now.minute() * 60 + now.second();
+    let next_sec = next_min * 60;
+    let delta = if next_sec > now_sec {
+        next_sec - now_sec
+    } else {
+        86_400 - now_sec + next_sec
+    };
+
+    Ok((active, Duration::from_secs(delta as u64)))
+}